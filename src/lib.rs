@@ -29,6 +29,56 @@ pub enum CheckSatResult {
     Unknown,
 }
 
+// The kind of a quantified formula built by SMTSolver::quantify.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Quantifier {
+    Forall,
+    Exists,
+}
+
+// A single constructor of an algebraic datatype declared with
+// declare_datatype.  It carries the constructor name together with its fields,
+// each a (selector_name, sort) pair.  A constructor with no fields denotes a
+// nullary constructor (e.g. an enum element); a single constructor with fields
+// recovers the record machinery as a special case.
+#[derive(Debug, Clone)]
+pub struct Constructor<S: Sort> {
+    name: String,
+    selectors: Vec<String>,
+    sorts: Vec<S>,
+}
+
+impl<S: Sort> Constructor<S> {
+    // Build a constructor named name whose fields are given by parallel slices
+    // of selector names and sorts, as in declare_record_sort.  Returns an
+    // APIError if the slices are not the same length.
+    pub fn new(name: &str, selectors: &[&str], sorts: &[&S]) -> SMTResult<Self> {
+        if selectors.len() != sorts.len() {
+            return Err(smt_err::SMTError::new_api(
+                "number of selectors does not match number of sorts",
+            ));
+        }
+        Ok(Constructor {
+            name: name.to_string(),
+            selectors: selectors.iter().map(|s| s.to_string()).collect(),
+            sorts: sorts.iter().map(|s| (*s).clone()).collect(),
+        })
+    }
+
+    // The name of the constructor.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // The fields of the constructor as (selector_name, sort) pairs.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &S)> {
+        self.selectors
+            .iter()
+            .map(String::as_str)
+            .zip(self.sorts.iter())
+    }
+}
+
 // An abstract data type for SMT sorts.
 pub trait Sort: Clone + std::fmt::Debug + Eq + std::hash::Hash + Sized {
     // Get a string representing the SMT-LIB name for the Sort.  The only
@@ -106,6 +156,20 @@ pub trait SMTSolver {
     // Return true iff the sort is a record sort.
     fn is_record_sort(&self, sort: &Self::S) -> bool;
 
+    // Declare an algebraic datatype with the given name and constructors, as in
+    // (declare-datatype ...).  This generalizes declare_record_sort: a single
+    // constructor with fields is a record, several nullary constructors form an
+    // enum, and self-referential sorts build lists and trees.  Each generated
+    // constructor, selector, and is-Ctor tester becomes available through
+    // apply_fun.  Returns an APIError if constructors is empty, if any
+    // constructor or selector name is reused, or if a record or datatype sort
+    // of the same name has already been declared.
+    fn declare_datatype(
+        &mut self,
+        name: &str,
+        constructors: &[Constructor<Self::S>],
+    ) -> SMTResult<Self::S>;
+
     ///////////////////////////////////////////////////////////////////////////
     // Functions                                                             //
     ///////////////////////////////////////////////////////////////////////////
@@ -166,10 +230,40 @@ pub trait SMTSolver {
     // terms rather than a vector of terms.
     fn apply_fun_refs(&self, f: &Function<Self::F>, args: &[&Self::T]) -> SMTResult<Self::T>;
 
+    // Create a bound variable with the given name and sort, for use as one of
+    // the bound variables of a quantified formula (see quantify).  The
+    // resulting term should only appear inside the body passed to quantify.
+    // Only InternalError errors are possible.
+    fn bound_var(&self, name: &str, sort: &Self::S) -> SMTResult<Self::T>;
+
+    // Build a quantified formula that binds the variables in bound (each the
+    // result of a call to bound_var) in body, producing a Boolean-sorted term.
+    // For each inner slice in patterns a :pattern trigger annotation is
+    // attached to the body in the order given; an empty patterns slice leaves
+    // the body unannotated.  Returns an APIError if body is not Boolean-sorted
+    // or if bound is empty.
+    fn quantify(
+        &self,
+        kind: Quantifier,
+        bound: &[&Self::T],
+        body: &Self::T,
+        patterns: &[&[&Self::T]],
+    ) -> SMTResult<Self::T>;
+
     ///////////////////////////////////////////////////////////////////////////
     // Solving                                                               //
     ///////////////////////////////////////////////////////////////////////////
 
+    // Install a sink that receives a faithful SMT-LIB 2 transcript of every
+    // mutating operation (declare_sort, declare_fun, declare_const,
+    // declare_record_sort, declare_datatype, assert, assert_labeled, push,
+    // pop, check_sat, get_value, get_unsat_core, and block_model) exactly as
+    // it is issued to the backend.  This is useful for debugging and for
+    // producing reproducible .smt2 scripts that can be replayed against any
+    // other solver.  Passing a new writer replaces any previously installed
+    // one.
+    fn tee(&mut self, w: Box<dyn std::io::Write>);
+
     // Returns the current level of the solver.  Initially the level is 0.  The
     // level increases with each push and decreases with each pop.
     fn level(&self) -> u32;
@@ -190,6 +284,18 @@ pub trait SMTSolver {
     // InternalError.
     fn assert(&mut self, t: &Self::T) -> SMTResult<bool>;
 
+    // Add an assertion t tagged with the given label, so that it can appear in
+    // an unsat core (see get_unsat_core).  The sort of the assertion must be
+    // Boolean.  Labels should be distinct; reusing a label is solver-dependent.
+    // Returns Ok(true) if successful.  Otherwise returns InternalError.
+    fn assert_labeled(&mut self, t: &Self::T, label: &str) -> SMTResult<bool>;
+
+    // After a call to check_sat that returns Unsat, return the labels of a
+    // subset of the labeled assertions that is jointly unsatisfiable.  Returns
+    // an APIError if the most recent check_sat did not return Unsat or if unsat
+    // core production is not enabled.
+    fn get_unsat_core(&mut self) -> SMTResult<Vec<String>>;
+
     // Check the satisfiability of all the assertions in the current solver
     // context.  Returns a CheckSatResult (see above).
     fn check_sat(&mut self) -> CheckSatResult;
@@ -200,6 +306,22 @@ pub trait SMTSolver {
     // not been called or if the most recent call returned unsat, an APIError
     // is returned.
     fn get_value(&mut self, t: &Self::T) -> SMTResult<Self::T>;
+
+    // After a call to check_sat that returns Sat, assert the negation of the
+    // current model, i.e. the negation of the conjunction of (= c v) over
+    // every constant declared so far, so that the next check_sat is forced to
+    // find a different model (if one exists).  This is the building block for
+    // all-SAT / solution-counting use cases.  Returns an APIError if the most
+    // recent check_sat did not return Sat or if no constants have been
+    // declared.
+    fn block_model(&mut self) -> SMTResult<bool>;
+
+    // Enumerate up to max distinct satisfying assignments by repeatedly
+    // calling check_sat, reading the value of each of terms, and blocking the
+    // model just found.  Stops early once check_sat returns anything other
+    // than Sat.  Returns one Vec<Self::T> per model found, in the order terms
+    // was given.
+    fn get_models(&mut self, terms: &[&Self::T], max: usize) -> SMTResult<Vec<Vec<Self::T>>>;
 }
 
 // Support for Z3 solver.
@@ -211,3 +333,7 @@ pub use z3::Z3Solver;
 pub fn new_z3_solver() -> Z3Solver {
     Z3Solver::new()
 }
+
+// Support for driving any SMT-LIB 2.6-compliant solver as a child process.
+pub mod process;
+pub use process::{ProcessSolver, SolverConf};
@@ -0,0 +1,1190 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+// A portable SMTSolver implementation that drives any SMT-LIB 2.6-compliant
+// solver as a child process, communicating over its stdin/stdout.  Unlike
+// Z3Solver, which links the Z3 C API, ProcessSolver requires no native
+// binding: every trait call is rendered into the corresponding textual
+// SMT-LIB command, written to the process, and the replies (`sat`, `unsat`,
+// `unknown`, and s-expression model values) are parsed back into terms.  This
+// gives the crate a solver-agnostic fallback and lets users target CVC5 or
+// Yices2 without a dedicated binding.
+
+use crate::smt_ops;
+use crate::smt_err::SMTError;
+use crate::{
+    CheckSatResult, Constructor, Function, Quantifier, SMTResult, SMTSolver, Sort, Term,
+    UninterpretedFunction,
+};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+// The solvers ProcessSolver knows how to spawn.  Each variant knows the name
+// of the binary to invoke and the command-line flags needed to put it into a
+// line-buffered SMT-LIB 2 read-eval-print loop on stdin/stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolverConf {
+    Z3,
+    CVC5,
+    Yices2,
+}
+
+impl SolverConf {
+    // The name of the executable to look up on the user's PATH.
+    fn binary(&self) -> &'static str {
+        match self {
+            SolverConf::Z3 => "z3",
+            SolverConf::CVC5 => "cvc5",
+            SolverConf::Yices2 => "yices-smt2",
+        }
+    }
+
+    // The flags that select SMT-LIB 2 input on stdin.
+    fn args(&self) -> &'static [&'static str] {
+        match self {
+            SolverConf::Z3 => &["-in", "-smt2"],
+            SolverConf::CVC5 => &["--lang", "smt2", "--incremental"],
+            SolverConf::Yices2 => &["--incremental"],
+        }
+    }
+}
+
+// A sort is represented by the SMT-LIB text that names it, e.g. "Int" or
+// "(_ BitVec 8)".
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProcessSort {
+    repr: String,
+}
+
+impl Sort for ProcessSort {
+    fn to_string(&self) -> SMTResult<String> {
+        Ok(self.repr.clone())
+    }
+}
+
+// A term is the SMT-LIB text that denotes it together with its sort, so that
+// get_sort and the various value constructors do not have to re-derive it.
+#[derive(Debug, Clone)]
+pub struct ProcessTerm {
+    repr: String,
+    sort: ProcessSort,
+}
+
+impl Term for ProcessTerm {
+    fn to_string(&self) -> SMTResult<String> {
+        Ok(self.repr.clone())
+    }
+
+    fn to_int(&self) -> SMTResult<i64> {
+        let s = self.repr.trim();
+        // Bitvector literals arrive either as #b... / #x..., or as
+        // (_ bvN width) as emitted by solvers such as CVC5; strip to the
+        // numeric core before parsing.
+        if let Some(bits) = s.strip_prefix("#b") {
+            return i64::from_str_radix(bits, 2)
+                .map_err(|_| SMTError::new_api("term does not fit in an i64"));
+        }
+        if let Some(hex) = s.strip_prefix("#x") {
+            return i64::from_str_radix(hex, 16)
+                .map_err(|_| SMTError::new_api("term does not fit in an i64"));
+        }
+        if let Some(rest) = s.strip_prefix("(_ bv") {
+            let digits = rest.split_whitespace().next().unwrap_or("");
+            return digits
+                .parse::<i64>()
+                .map_err(|_| SMTError::new_api("term does not fit in an i64"));
+        }
+        // Negative Int/Real constants round-trip through the SMT-LIB prefix
+        // form "(- n)" emitted by const_from_string; strip it before the
+        // numeric parse.
+        let (negative, s) = match s.strip_prefix("(-").and_then(|s| s.strip_suffix(')')) {
+            Some(inner) => (true, inner.trim()),
+            None => (false, s),
+        };
+        // Reals with a trailing ".0" denote integral values; anything else is
+        // not representable as an i64.
+        let core = s.strip_suffix(".0").unwrap_or(s);
+        let value: i64 = core
+            .parse()
+            .map_err(|_| SMTError::new_api("term is not an integral constant"))?;
+        Ok(if negative { -value } else { value })
+    }
+}
+
+// An uninterpreted function is represented by its declared name.
+#[derive(Debug, Clone)]
+pub struct ProcessFun {
+    name: String,
+    ret: ProcessSort,
+}
+
+impl UninterpretedFunction for ProcessFun {
+    fn to_string(&self) -> SMTResult<String> {
+        Ok(self.name.clone())
+    }
+}
+
+// The running backend: the spawned child and the pipes to talk to it.  It is
+// kept behind a RefCell so that the &self trait methods (declare_sort,
+// declare_fun, declare_const) can still write commands, and is populated
+// lazily on first use.
+struct Backend {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+// ProcessSolver lazily spawns its backend on first use, so that new() can stay
+// infallible like the rest of the SMTSolver implementations; any failure to
+// start the binary surfaces as an InternalError on the first command.
+pub struct ProcessSolver {
+    conf: SolverConf,
+    backend: RefCell<Option<Backend>>,
+    level: Cell<u32>,
+    // Field layout of each record sort, keyed by sort name, used to render
+    // record literals and selector/update applications.
+    records: RefCell<HashMap<String, Vec<String>>>,
+    // Result sorts of datatype constructors (constructor name -> datatype sort
+    // repr) and selectors (selector name -> field sort repr), used to give
+    // apply_fun the right result sort for datatype functions.
+    constructors: RefCell<HashMap<String, String>>,
+    selectors: RefCell<HashMap<String, String>>,
+    // Names of datatype sorts declared with declare_datatype (disjoint from
+    // records, which track their own sort names in `records`), used to reject
+    // a name that is already in use.
+    datatypes: RefCell<HashSet<String>>,
+    // Name and sort of every constant declared with declare_const, in
+    // declaration order, used by block_model to rebuild the current model as
+    // a conjunction of equalities.
+    consts: RefCell<Vec<(String, ProcessSort)>>,
+    // Whether (set-option :produce-unsat-cores true) has been sent yet; kept
+    // off by default since it pushes many solvers into a slower, less
+    // incremental mode, and turned on lazily by the first assert_labeled call.
+    unsat_cores_enabled: Cell<bool>,
+    last_check: Cell<Option<CheckSatResult>>,
+    // Optional transcript sink; every command written to the backend is echoed
+    // here verbatim.  See the tee method.
+    transcript: RefCell<Option<Box<dyn Write>>>,
+}
+
+impl ProcessSolver {
+    // Create a ProcessSolver driving the given backend.  The process itself is
+    // not spawned until the first command is issued.
+    pub fn with_conf(conf: SolverConf) -> ProcessSolver {
+        ProcessSolver {
+            conf,
+            backend: RefCell::new(None),
+            level: Cell::new(0),
+            records: RefCell::new(HashMap::new()),
+            constructors: RefCell::new(HashMap::new()),
+            selectors: RefCell::new(HashMap::new()),
+            datatypes: RefCell::new(HashSet::new()),
+            consts: RefCell::new(Vec::new()),
+            unsat_cores_enabled: Cell::new(false),
+            last_check: Cell::new(None),
+            transcript: RefCell::new(None),
+        }
+    }
+
+    // Spawn the backend process if it is not already running and configure it
+    // for model production.
+    fn ensure_started(&self) -> SMTResult<()> {
+        if self.backend.borrow().is_some() {
+            return Ok(());
+        }
+        let mut child = Command::new(self.conf.binary())
+            .args(self.conf.args())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| SMTError::new_internal(&format!("could not start solver: {}", e)))?;
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| SMTError::new_internal("could not capture solver stdin"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| SMTError::new_internal("could not capture solver stdout"))?;
+        *self.backend.borrow_mut() = Some(Backend {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+        });
+        self.raw_send("(set-option :print-success false)")?;
+        self.raw_send("(set-option :produce-models true)")?;
+        Ok(())
+    }
+
+    // Write a single command line to the backend, starting it if necessary.
+    fn send(&self, cmd: &str) -> SMTResult<()> {
+        self.ensure_started()?;
+        self.raw_send(cmd)
+    }
+
+    // Turn on unsat-core production the first time an assertion is labeled.
+    // Many solvers run slower or less incrementally with this option set, so
+    // it is kept off for sessions that never call assert_labeled rather than
+    // enabled unconditionally at startup.  get_unsat_core does not call this:
+    // it reports an APIError if core production was never turned on, since
+    // enabling it after the check_sat that produced Unsat would not actually
+    // make a core available for that query.
+    fn ensure_unsat_cores(&self) -> SMTResult<()> {
+        if self.unsat_cores_enabled.get() {
+            return Ok(());
+        }
+        self.send("(set-option :produce-unsat-cores true)")?;
+        self.unsat_cores_enabled.set(true);
+        Ok(())
+    }
+
+    // Write a command line to an already-running backend, echoing it to the
+    // transcript sink first if one is installed.
+    fn raw_send(&self, cmd: &str) -> SMTResult<()> {
+        if let Some(w) = self.transcript.borrow_mut().as_mut() {
+            writeln!(w, "{}", cmd)
+                .and_then(|_| w.flush())
+                .map_err(|e| SMTError::new_internal(&format!("write to transcript failed: {}", e)))?;
+        }
+        let mut guard = self.backend.borrow_mut();
+        let backend = guard
+            .as_mut()
+            .ok_or_else(|| SMTError::new_internal("solver is not running"))?;
+        writeln!(backend.stdin, "{}", cmd)
+            .and_then(|_| backend.stdin.flush())
+            .map_err(|e| SMTError::new_internal(&format!("write to solver failed: {}", e)))
+    }
+
+    // Read one top-level s-expression (or bare token) from the backend,
+    // balancing parentheses so that multi-line model values are returned
+    // whole.
+    fn read_sexpr(&self) -> SMTResult<String> {
+        let mut guard = self.backend.borrow_mut();
+        let backend = guard
+            .as_mut()
+            .ok_or_else(|| SMTError::new_internal("solver is not running"))?;
+        Self::read_balanced_sexpr(&mut backend.stdout)
+    }
+
+    // Read lines from r, balancing parentheses, until a complete top-level
+    // s-expression (or bare token) has accumulated; return it trimmed.  Pulled
+    // out of read_sexpr so it can be unit-tested against an in-memory reader
+    // instead of a live solver process.
+    fn read_balanced_sexpr<R: BufRead>(r: &mut R) -> SMTResult<String> {
+        let mut out = String::new();
+        let mut depth: i32 = 0;
+        loop {
+            let mut line = String::new();
+            let n = r
+                .read_line(&mut line)
+                .map_err(|e| SMTError::new_internal(&format!("read from solver failed: {}", e)))?;
+            if n == 0 {
+                return Err(SMTError::new_internal("solver closed its output"));
+            }
+            for c in line.chars() {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+            }
+            out.push_str(&line);
+            if depth <= 0 && !out.trim().is_empty() {
+                return Ok(out.trim().to_string());
+            }
+        }
+    }
+
+    // Render the symbol of a built-in operator as its SMT-LIB name.  Operators
+    // the textual backend does not know how to emit yield an UnsupportedError,
+    // matching the trait's contract for apply_fun.
+    fn op_symbol(op: &smt_ops::Fn) -> SMTResult<String> {
+        use smt_ops::Fn::*;
+        let s = match op {
+            Not => "not".to_string(),
+            And => "and".to_string(),
+            Or => "or".to_string(),
+            Xor => "xor".to_string(),
+            Implies => "=>".to_string(),
+            Eq => "=".to_string(),
+            Distinct => "distinct".to_string(),
+            Ite => "ite".to_string(),
+            Neg => "-".to_string(),
+            Add => "+".to_string(),
+            Sub => "-".to_string(),
+            Mul => "*".to_string(),
+            Div => "/".to_string(),
+            IntDiv => "div".to_string(),
+            Mod => "mod".to_string(),
+            Abs => "abs".to_string(),
+            Le => "<=".to_string(),
+            Lt => "<".to_string(),
+            Ge => ">=".to_string(),
+            Gt => ">".to_string(),
+            ToReal => "to_real".to_string(),
+            ToInt => "to_int".to_string(),
+            BvNot => "bvnot".to_string(),
+            BvAnd => "bvand".to_string(),
+            BvOr => "bvor".to_string(),
+            BvXor => "bvxor".to_string(),
+            BvNeg => "bvneg".to_string(),
+            BvAdd => "bvadd".to_string(),
+            BvSub => "bvsub".to_string(),
+            BvMul => "bvmul".to_string(),
+            BvUdiv => "bvudiv".to_string(),
+            BvUrem => "bvurem".to_string(),
+            BvShl => "bvshl".to_string(),
+            BvLshr => "bvlshr".to_string(),
+            BvUlt => "bvult".to_string(),
+            Select => "select".to_string(),
+            Store => "store".to_string(),
+            FpLiteral => "fp".to_string(),
+            FpAbs => "fp.abs".to_string(),
+            FpNeg => "fp.neg".to_string(),
+            FpAdd => "fp.add".to_string(),
+            FpSub => "fp.sub".to_string(),
+            FpMul => "fp.mul".to_string(),
+            FpDiv => "fp.div".to_string(),
+            FpFma => "fp.fma".to_string(),
+            FpSqrt => "fp.sqrt".to_string(),
+            FpRem => "fp.rem".to_string(),
+            FpRoundToIntegral => "fp.roundToIntegral".to_string(),
+            FpMin => "fp.min".to_string(),
+            FpMax => "fp.max".to_string(),
+            FpLeq => "fp.leq".to_string(),
+            FpLt => "fp.lt".to_string(),
+            FpGeq => "fp.geq".to_string(),
+            FpGt => "fp.gt".to_string(),
+            FpEq => "fp.eq".to_string(),
+            FpIsNormal => "fp.isNormal".to_string(),
+            FpIsSubnormal => "fp.isSubnormal".to_string(),
+            FpIsZero => "fp.isZero".to_string(),
+            FpIsInfinite => "fp.isInfinite".to_string(),
+            FpIsNaN => "fp.isNaN".to_string(),
+            FpIsNegative => "fp.isNegative".to_string(),
+            FpIsPositive => "fp.isPositive".to_string(),
+            RecordSelect(field) => field.to_string(),
+            DtConstruct(ctor) => ctor.to_string(),
+            DtSelect(sel) => sel.to_string(),
+            DtTest(ctor) => format!("(_ is {})", ctor),
+            _ => {
+                return Err(SMTError::new_unsupported(
+                    "operator not supported by the process backend",
+                ))
+            }
+        };
+        Ok(s)
+    }
+
+    // The width n of a "(_ BitVec n)" sort repr, used to size the
+    // FloatingPoint sort built by the fp literal constructor.
+    fn bitvec_width(sort: &ProcessSort) -> Option<u32> {
+        sort.repr
+            .strip_prefix("(_ BitVec ")
+            .and_then(|s| s.strip_suffix(')'))
+            .and_then(|s| s.trim().parse().ok())
+    }
+
+    // The result sort of applying op to arguments of the given sorts.  Boolean
+    // connectives, comparisons, and datatype and floating-point testers
+    // produce Bool; datatype constructors and selectors produce the sort
+    // recorded at declaration time; the fp literal constructor produces a
+    // FloatingPoint sort sized from its exponent and significand bitvector
+    // arguments; rounded floating-point operators take their sort from the
+    // first argument after the leading rounding mode; to_real and to_int
+    // produce Real and Int; an array read produces the element sort of its
+    // array argument; everything else is assumed to agree with the sort of its
+    // first argument, which is correct for the remaining arithmetic,
+    // bitvector, and array-store operators.
+    fn result_sort(&self, op: &smt_ops::Fn, args: &[&ProcessSort]) -> ProcessSort {
+        use smt_ops::Fn::*;
+        let bool_sort = || ProcessSort {
+            repr: "Bool".to_string(),
+        };
+        match op {
+            Not | And | Or | Xor | Implies | Eq | Distinct | Le | Lt | Ge | Gt | BvUlt
+            | DtTest(_) | FpLeq | FpLt | FpGeq | FpGt | FpEq | FpIsNormal | FpIsSubnormal
+            | FpIsZero | FpIsInfinite | FpIsNaN | FpIsNegative | FpIsPositive => bool_sort(),
+            DtConstruct(ctor) => self
+                .constructors
+                .borrow()
+                .get(*ctor)
+                .map(|r| ProcessSort { repr: r.clone() })
+                .unwrap_or_else(bool_sort),
+            DtSelect(sel) => self
+                .selectors
+                .borrow()
+                .get(*sel)
+                .map(|r| ProcessSort { repr: r.clone() })
+                .unwrap_or_else(bool_sort),
+            FpLiteral => {
+                let eb = args.get(1).and_then(|s| Self::bitvec_width(s));
+                let sb = args.get(2).and_then(|s| Self::bitvec_width(s));
+                match (eb, sb) {
+                    (Some(eb), Some(sb)) => ProcessSort {
+                        repr: format!("(_ FloatingPoint {} {})", eb, sb + 1),
+                    },
+                    _ => bool_sort(),
+                }
+            }
+            FpAdd | FpSub | FpMul | FpDiv | FpSqrt | FpRoundToIntegral | FpFma => args
+                .get(1)
+                .map(|s| (*s).clone())
+                .unwrap_or_else(bool_sort),
+            ToReal => ProcessSort {
+                repr: "Real".to_string(),
+            },
+            ToInt => ProcessSort {
+                repr: "Int".to_string(),
+            },
+            // "(Array I E)" yields E; I may itself be parenthesized.
+            Select => args
+                .first()
+                .and_then(|s| s.repr.strip_prefix("(Array ")?.strip_suffix(')'))
+                .and_then(|inner| {
+                    let mut depth = 0i32;
+                    let end = inner.find(|c: char| {
+                        depth += (c == '(') as i32 - (c == ')') as i32;
+                        c == ' ' && depth == 0
+                    })?;
+                    Some(ProcessSort {
+                        repr: inner[end..].trim().to_string(),
+                    })
+                })
+                .unwrap_or_else(bool_sort),
+            _ => args.first().map(|s| (*s).clone()).unwrap_or_else(bool_sort),
+        }
+    }
+
+    // Extract the value from a get-value reply of the shape
+    // "((<term_repr> <value>))", given the repr of the term that was queried.
+    // Pulled out of get_value so it can be unit-tested without a live solver.
+    fn parse_get_value_reply(reply: &str, term_repr: &str) -> SMTResult<String> {
+        let inner = reply
+            .trim()
+            .strip_prefix('(')
+            .and_then(|s| s.strip_suffix(')'))
+            .map(str::trim)
+            .and_then(|s| s.strip_prefix('('))
+            .and_then(|s| s.strip_suffix(')'))
+            .ok_or_else(|| SMTError::new_internal("malformed get-value reply"))?;
+        Ok(inner
+            .strip_prefix(term_repr)
+            .map(str::trim)
+            .unwrap_or(inner)
+            .to_string())
+    }
+
+    // Parse a get-unsat-core reply, a parenthesized list of the labels that
+    // make up the core, e.g. "(a c)"; an empty core is reported as "()".
+    // Pulled out of get_unsat_core so it can be unit-tested without a live
+    // solver.
+    fn parse_unsat_core_reply(reply: &str) -> SMTResult<Vec<String>> {
+        let inner = reply
+            .trim()
+            .strip_prefix('(')
+            .and_then(|s| s.strip_suffix(')'))
+            .ok_or_else(|| SMTError::new_internal("malformed get-unsat-core reply"))?;
+        Ok(inner.split_whitespace().map(str::to_string).collect())
+    }
+
+    // Assemble the application "(sym a1 a2 ...)", or just "sym" when there are
+    // no arguments.
+    fn application(sym: &str, args: &[&ProcessTerm]) -> String {
+        if args.is_empty() {
+            sym.to_string()
+        } else {
+            let mut s = String::from("(");
+            s.push_str(sym);
+            for a in args {
+                s.push(' ');
+                s.push_str(&a.repr);
+            }
+            s.push(')');
+            s
+        }
+    }
+}
+
+impl SMTSolver for ProcessSolver {
+    type S = ProcessSort;
+    type T = ProcessTerm;
+    type F = ProcessFun;
+
+    fn new() -> Self {
+        ProcessSolver::with_conf(SolverConf::Z3)
+    }
+
+    fn get_sort(&self, t: &Self::T) -> SMTResult<Self::S> {
+        Ok(t.sort.clone())
+    }
+
+    fn declare_sort(&self, name: &str) -> SMTResult<Self::S> {
+        self.send(&format!("(declare-sort {} 0)", name))?;
+        Ok(ProcessSort {
+            repr: name.to_string(),
+        })
+    }
+
+    fn lookup_sort(&self, s: smt_ops::Sorts) -> SMTResult<Self::S> {
+        use smt_ops::Sorts::*;
+        let repr = match s {
+            Bool => "Bool".to_string(),
+            Int => "Int".to_string(),
+            Real => "Real".to_string(),
+            BitVec(n) => format!("(_ BitVec {})", n),
+            FloatingPoint(eb, sb) => format!("(_ FloatingPoint {} {})", eb, sb),
+            RoundingMode => "RoundingMode".to_string(),
+            Array => {
+                return Err(SMTError::new_api(
+                    "Array is a sort constructor; use apply_sort",
+                ))
+            }
+        };
+        Ok(ProcessSort { repr })
+    }
+
+    fn apply_sort(&self, s: smt_ops::Sorts, s1: &Self::S, s2: &Self::S) -> SMTResult<Self::S> {
+        match s {
+            smt_ops::Sorts::Array => Ok(ProcessSort {
+                repr: format!("(Array {} {})", s1.repr, s2.repr),
+            }),
+            _ => Err(SMTError::new_api("sort is not a binary sort constructor")),
+        }
+    }
+
+    fn declare_record_sort(
+        &mut self,
+        name: &str,
+        fields: &[&str],
+        sorts: &[&Self::S],
+    ) -> SMTResult<Self::S> {
+        if fields.len() != sorts.len() {
+            return Err(SMTError::new_api(
+                "number of fields does not match number of sorts",
+            ));
+        }
+        let mut seen = std::collections::HashSet::new();
+        for f in fields {
+            if !seen.insert(*f) {
+                return Err(SMTError::new_api("record field names must be distinct"));
+            }
+        }
+        if self.records.borrow().contains_key(name) {
+            return Err(SMTError::new_api("a record of that name already exists"));
+        }
+        // A record is a single-constructor datatype whose selectors are its
+        // fields.
+        let mut ctor = format!("(mk-{}", name);
+        for (f, s) in fields.iter().zip(sorts.iter()) {
+            ctor.push_str(&format!(" ({} {})", f, s.repr));
+        }
+        ctor.push(')');
+        self.send(&format!("(declare-datatype {} (({})))", name, ctor))?;
+        self.records.borrow_mut().insert(
+            name.to_string(),
+            fields.iter().map(|f| f.to_string()).collect(),
+        );
+        Ok(ProcessSort {
+            repr: name.to_string(),
+        })
+    }
+
+    fn is_record_sort(&self, sort: &Self::S) -> bool {
+        self.records.borrow().contains_key(&sort.repr)
+    }
+
+    fn declare_datatype(
+        &mut self,
+        name: &str,
+        constructors: &[Constructor<Self::S>],
+    ) -> SMTResult<Self::S> {
+        if constructors.is_empty() {
+            return Err(SMTError::new_api(
+                "a datatype must have at least one constructor",
+            ));
+        }
+        if self.records.borrow().contains_key(name) || self.datatypes.borrow().contains(name) {
+            return Err(SMTError::new_api("a sort of that name already exists"));
+        }
+        // Validate all constructor and selector names before touching any
+        // persistent state, so that a rejected call leaves the solver exactly
+        // as it was: distinct within this call, and not already claimed by an
+        // earlier declare_datatype/declare_record_sort.
+        let records = self.records.borrow();
+        let claimed_by_record = |n: &str| {
+            records
+                .iter()
+                .any(|(r, fields)| n == format!("mk-{}", r) || fields.iter().any(|f| f == n))
+        };
+        let mut seen = HashSet::new();
+        for c in constructors {
+            if !seen.insert(c.name().to_string())
+                || self.constructors.borrow().contains_key(c.name())
+                || claimed_by_record(c.name())
+            {
+                return Err(SMTError::new_api("constructor names must be distinct"));
+            }
+            for (sel, _) in c.fields() {
+                if !seen.insert(sel.to_string())
+                    || self.selectors.borrow().contains_key(sel)
+                    || claimed_by_record(sel)
+                {
+                    return Err(SMTError::new_api("selector names must be distinct"));
+                }
+            }
+        }
+        // Render "(ctor (sel sort) ...)" for each constructor, into local
+        // variables only; nothing is committed to persistent state until
+        // send succeeds, so a failed send (backend not started, broken pipe)
+        // leaves these names free for a later, correct call to reuse.
+        let mut rendered = Vec::with_capacity(constructors.len());
+        for c in constructors {
+            let mut body = String::from("(");
+            body.push_str(c.name());
+            for (sel, sort) in c.fields() {
+                body.push_str(&format!(" ({} {})", sel, sort.repr));
+            }
+            body.push(')');
+            rendered.push(body);
+        }
+        self.send(&format!(
+            "(declare-datatype {} ({}))",
+            name,
+            rendered.join(" ")
+        ))?;
+        for c in constructors {
+            for (sel, sort) in c.fields() {
+                self.selectors
+                    .borrow_mut()
+                    .insert(sel.to_string(), sort.repr.clone());
+            }
+            self.constructors
+                .borrow_mut()
+                .insert(c.name().to_string(), name.to_string());
+        }
+        self.datatypes.borrow_mut().insert(name.to_string());
+        Ok(ProcessSort {
+            repr: name.to_string(),
+        })
+    }
+
+    fn declare_fun(&self, name: &str, args: &[&Self::S], sort: &Self::S) -> SMTResult<Self::F> {
+        let arg_sorts: Vec<String> = args.iter().map(|s| s.repr.clone()).collect();
+        self.send(&format!(
+            "(declare-fun {} ({}) {})",
+            name,
+            arg_sorts.join(" "),
+            sort.repr
+        ))?;
+        Ok(ProcessFun {
+            name: name.to_string(),
+            ret: sort.clone(),
+        })
+    }
+
+    fn declare_const(&self, name: &str, sort: &Self::S) -> SMTResult<Self::T> {
+        self.send(&format!("(declare-const {} {})", name, sort.repr))?;
+        self.consts
+            .borrow_mut()
+            .push((name.to_string(), sort.clone()));
+        Ok(ProcessTerm {
+            repr: name.to_string(),
+            sort: sort.clone(),
+        })
+    }
+
+    fn lookup_const(&self, f: smt_ops::Fn) -> SMTResult<Self::T> {
+        use smt_ops::Fn::*;
+        let (repr, sort) = match f {
+            True => ("true".to_string(), "Bool".to_string()),
+            False => ("false".to_string(), "Bool".to_string()),
+            RoundNearestTiesToEven => ("RNE".to_string(), "RoundingMode".to_string()),
+            RoundNearestTiesToAway => ("RNA".to_string(), "RoundingMode".to_string()),
+            RoundTowardPositive => ("RTP".to_string(), "RoundingMode".to_string()),
+            RoundTowardNegative => ("RTN".to_string(), "RoundingMode".to_string()),
+            RoundTowardZero => ("RTZ".to_string(), "RoundingMode".to_string()),
+            _ => return Err(SMTError::new_api("operator is not a built-in constant")),
+        };
+        Ok(ProcessTerm {
+            repr,
+            sort: ProcessSort { repr: sort },
+        })
+    }
+
+    fn const_from_int(&self, value: i64, sort: &Self::S) -> SMTResult<Self::T> {
+        self.const_from_string(&value.to_string(), sort)
+    }
+
+    fn const_from_string(&self, value: &str, sort: &Self::S) -> SMTResult<Self::T> {
+        let repr = match sort.repr.as_str() {
+            "Int" => {
+                if let Some(rest) = value.strip_prefix('-') {
+                    format!("(- {})", rest)
+                } else {
+                    value.to_string()
+                }
+            }
+            "Real" => {
+                let v = if value.contains('.') {
+                    value.to_string()
+                } else {
+                    format!("{}.0", value)
+                };
+                if let Some(rest) = v.strip_prefix('-') {
+                    format!("(- {})", rest)
+                } else {
+                    v
+                }
+            }
+            other if other.starts_with("(_ BitVec ") => {
+                let width: &str = other
+                    .trim_start_matches("(_ BitVec ")
+                    .trim_end_matches(')')
+                    .trim();
+                format!("(_ bv{} {})", value, width)
+            }
+            _ => {
+                return Err(SMTError::new_api(
+                    "const_from_string expects an Int, Real, or BitVec sort",
+                ))
+            }
+        };
+        Ok(ProcessTerm {
+            repr,
+            sort: sort.clone(),
+        })
+    }
+
+    fn record_const(&self, record_sort: &Self::S, field_values: &[Self::T]) -> SMTResult<Self::T> {
+        let refs: Vec<&Self::T> = field_values.iter().collect();
+        self.record_const_refs(record_sort, &refs)
+    }
+
+    fn record_const_refs(
+        &self,
+        record_sort: &Self::S,
+        field_values: &[&Self::T],
+    ) -> SMTResult<Self::T> {
+        if !self.is_record_sort(record_sort) {
+            return Err(SMTError::new_api("sort is not a record sort"));
+        }
+        let mut repr = format!("(mk-{}", record_sort.repr);
+        for v in field_values {
+            repr.push(' ');
+            repr.push_str(&v.repr);
+        }
+        repr.push(')');
+        Ok(ProcessTerm {
+            repr,
+            sort: record_sort.clone(),
+        })
+    }
+
+    fn apply_fun(&self, f: &Function<Self::F>, args: &[Self::T]) -> SMTResult<Self::T> {
+        let refs: Vec<&Self::T> = args.iter().collect();
+        self.apply_fun_refs(f, &refs)
+    }
+
+    fn apply_fun_refs(&self, f: &Function<Self::F>, args: &[&Self::T]) -> SMTResult<Self::T> {
+        match f {
+            Function::UF(uf) => Ok(ProcessTerm {
+                repr: Self::application(&uf.name, args),
+                sort: uf.ret.clone(),
+            }),
+            Function::Op(op) => {
+                let sym = Self::op_symbol(op)?;
+                let arg_sorts: Vec<&Self::S> = args.iter().map(|a| &a.sort).collect();
+                Ok(ProcessTerm {
+                    repr: Self::application(&sym, args),
+                    sort: self.result_sort(op, &arg_sorts),
+                })
+            }
+        }
+    }
+
+    fn tee(&mut self, w: Box<dyn Write>) {
+        *self.transcript.borrow_mut() = Some(w);
+    }
+
+    fn bound_var(&self, name: &str, sort: &Self::S) -> SMTResult<Self::T> {
+        Ok(ProcessTerm {
+            repr: name.to_string(),
+            sort: sort.clone(),
+        })
+    }
+
+    fn quantify(
+        &self,
+        kind: Quantifier,
+        bound: &[&Self::T],
+        body: &Self::T,
+        patterns: &[&[&Self::T]],
+    ) -> SMTResult<Self::T> {
+        if bound.is_empty() {
+            return Err(SMTError::new_api(
+                "a quantified formula must bind at least one variable",
+            ));
+        }
+        if body.sort.repr != "Bool" {
+            return Err(SMTError::new_api("the body of a quantifier must be Boolean"));
+        }
+        let keyword = match kind {
+            Quantifier::Forall => "forall",
+            Quantifier::Exists => "exists",
+        };
+        let bindings: Vec<String> = bound
+            .iter()
+            .map(|v| format!("({} {})", v.repr, v.sort.repr))
+            .collect();
+        // With triggers the body is wrapped in an annotation term carrying one
+        // :pattern per supplied group.
+        let annotated = if patterns.is_empty() {
+            body.repr.clone()
+        } else {
+            let mut s = format!("(! {}", body.repr);
+            for group in patterns {
+                let terms: Vec<&str> = group.iter().map(|t| t.repr.as_str()).collect();
+                s.push_str(&format!(" :pattern ({})", terms.join(" ")));
+            }
+            s.push(')');
+            s
+        };
+        Ok(ProcessTerm {
+            repr: format!("({} ({}) {})", keyword, bindings.join(" "), annotated),
+            sort: ProcessSort {
+                repr: "Bool".to_string(),
+            },
+        })
+    }
+
+    fn level(&self) -> u32 {
+        self.level.get()
+    }
+
+    fn push(&mut self, n: u32) -> SMTResult<bool> {
+        self.send(&format!("(push {})", n))?;
+        self.level.set(self.level.get() + n);
+        Ok(true)
+    }
+
+    fn pop(&mut self, n: u32) -> SMTResult<bool> {
+        if n > self.level.get() {
+            return Err(SMTError::new_api("cannot pop below level 0"));
+        }
+        self.send(&format!("(pop {})", n))?;
+        self.level.set(self.level.get() - n);
+        Ok(true)
+    }
+
+    fn assert(&mut self, t: &Self::T) -> SMTResult<bool> {
+        self.send(&format!("(assert {})", t.repr))?;
+        Ok(true)
+    }
+
+    fn assert_labeled(&mut self, t: &Self::T, label: &str) -> SMTResult<bool> {
+        self.ensure_unsat_cores()?;
+        self.send(&format!("(assert (! {} :named {}))", t.repr, label))?;
+        Ok(true)
+    }
+
+    fn get_unsat_core(&mut self) -> SMTResult<Vec<String>> {
+        if self.last_check.get() != Some(CheckSatResult::Unsat) {
+            return Err(SMTError::new_api(
+                "get_unsat_core requires the last check_sat to have returned Unsat",
+            ));
+        }
+        if !self.unsat_cores_enabled.get() {
+            return Err(SMTError::new_api(
+                "get_unsat_core requires unsat-core production to have been enabled by a prior assert_labeled call",
+            ));
+        }
+        self.send("(get-unsat-core)")?;
+        let reply = self.read_sexpr()?;
+        Self::parse_unsat_core_reply(&reply)
+    }
+
+    fn check_sat(&mut self) -> CheckSatResult {
+        // The trait signature for check_sat cannot report errors, so a failure
+        // to communicate with the backend is reported as Unknown.
+        if self.send("(check-sat)").is_err() {
+            return CheckSatResult::Unknown;
+        }
+        let result = match self.read_sexpr().as_deref() {
+            Ok("sat") => CheckSatResult::Sat,
+            Ok("unsat") => CheckSatResult::Unsat,
+            _ => CheckSatResult::Unknown,
+        };
+        self.last_check.set(Some(result));
+        result
+    }
+
+    fn get_value(&mut self, t: &Self::T) -> SMTResult<Self::T> {
+        match self.last_check.get() {
+            Some(CheckSatResult::Sat) => {}
+            _ => {
+                return Err(SMTError::new_api(
+                    "get_value requires the last check_sat to have returned Sat",
+                ))
+            }
+        }
+        self.send(&format!("(get-value ({}))", t.repr))?;
+        let reply = self.read_sexpr()?;
+        let value = Self::parse_get_value_reply(&reply, &t.repr)?;
+        Ok(ProcessTerm {
+            repr: value,
+            sort: t.sort.clone(),
+        })
+    }
+
+    fn block_model(&mut self) -> SMTResult<bool> {
+        if self.last_check.get() != Some(CheckSatResult::Sat) {
+            return Err(SMTError::new_api(
+                "block_model requires the last check_sat to have returned Sat",
+            ));
+        }
+        let consts = self.consts.borrow().clone();
+        if consts.is_empty() {
+            return Err(SMTError::new_api(
+                "block_model requires at least one declared constant",
+            ));
+        }
+        let mut equalities = Vec::with_capacity(consts.len());
+        for (name, sort) in &consts {
+            let term = ProcessTerm {
+                repr: name.clone(),
+                sort: sort.clone(),
+            };
+            let value = self.get_value(&term)?;
+            equalities.push(format!("(= {} {})", name, value.repr));
+        }
+        self.send(&format!("(assert (not (and {})))", equalities.join(" ")))?;
+        Ok(true)
+    }
+
+    fn get_models(&mut self, terms: &[&Self::T], max: usize) -> SMTResult<Vec<Vec<Self::T>>> {
+        let mut models = Vec::with_capacity(max);
+        while models.len() < max {
+            if self.check_sat() != CheckSatResult::Sat {
+                break;
+            }
+            let mut model = Vec::with_capacity(terms.len());
+            for t in terms {
+                model.push(self.get_value(t)?);
+            }
+            models.push(model);
+            self.block_model()?;
+        }
+        Ok(models)
+    }
+}
+
+impl Drop for ProcessSolver {
+    fn drop(&mut self) {
+        // Ask the backend to exit cleanly, then reap it.
+        let _ = self.raw_send("(exit)");
+        if let Some(mut backend) = self.backend.borrow_mut().take() {
+            let _ = backend.child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn term(repr: &str, sort: &str) -> ProcessTerm {
+        ProcessTerm {
+            repr: repr.to_string(),
+            sort: ProcessSort {
+                repr: sort.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn to_int_parses_binary_and_hex_bitvectors() {
+        assert_eq!(term("#b0101", "(_ BitVec 4)").to_int().unwrap(), 5);
+        assert_eq!(term("#xff", "(_ BitVec 8)").to_int().unwrap(), 255);
+    }
+
+    #[test]
+    fn to_int_parses_indexed_bitvector_literal() {
+        assert_eq!(term("(_ bv5 8)", "(_ BitVec 8)").to_int().unwrap(), 5);
+    }
+
+    #[test]
+    fn to_int_parses_negative_prefix_form() {
+        assert_eq!(term("(- 5)", "Int").to_int().unwrap(), -5);
+        assert_eq!(term("(- 5.0)", "Real").to_int().unwrap(), -5);
+    }
+
+    #[test]
+    fn to_int_parses_plain_int_and_real() {
+        assert_eq!(term("5", "Int").to_int().unwrap(), 5);
+        assert_eq!(term("5.0", "Real").to_int().unwrap(), 5);
+    }
+
+    #[test]
+    fn to_int_rejects_non_integral_values() {
+        assert!(term("5.5", "Real").to_int().is_err());
+    }
+
+    #[test]
+    fn read_balanced_sexpr_waits_for_matching_parens() {
+        let mut r = Cursor::new("(a (b c)\n d)\n".as_bytes());
+        let s = ProcessSolver::read_balanced_sexpr(&mut r).unwrap();
+        assert_eq!(s, "(a (b c)\n d)");
+    }
+
+    #[test]
+    fn read_balanced_sexpr_accepts_bare_tokens() {
+        let mut r = Cursor::new("sat\n".as_bytes());
+        let s = ProcessSolver::read_balanced_sexpr(&mut r).unwrap();
+        assert_eq!(s, "sat");
+    }
+
+    #[test]
+    fn read_balanced_sexpr_errors_on_closed_stream() {
+        let mut r = Cursor::new("".as_bytes());
+        assert!(ProcessSolver::read_balanced_sexpr(&mut r).is_err());
+    }
+
+    #[test]
+    fn parse_get_value_reply_extracts_the_value() {
+        let reply = "((x 5))";
+        assert_eq!(
+            ProcessSolver::parse_get_value_reply(reply, "x").unwrap(),
+            "5"
+        );
+    }
+
+    #[test]
+    fn parse_get_value_reply_handles_compound_terms_and_values() {
+        let reply = "(((f x) (- 5)))";
+        assert_eq!(
+            ProcessSolver::parse_get_value_reply(reply, "(f x)").unwrap(),
+            "(- 5)"
+        );
+    }
+
+    #[test]
+    fn parse_get_value_reply_rejects_malformed_input() {
+        assert!(ProcessSolver::parse_get_value_reply("not-an-sexpr", "x").is_err());
+    }
+
+    #[test]
+    fn declare_datatype_rejects_empty_constructors() {
+        let mut solver = ProcessSolver::with_conf(SolverConf::Z3);
+        assert!(solver.declare_datatype("D", &[]).is_err());
+    }
+
+    #[test]
+    fn declare_datatype_rejects_duplicate_constructor_names_within_one_call() {
+        let mut solver = ProcessSolver::with_conf(SolverConf::Z3);
+        let int_sort = ProcessSort {
+            repr: "Int".to_string(),
+        };
+        let ctors = [
+            Constructor::<ProcessSort>::new("C", &[], &[]).unwrap(),
+            Constructor::new("C", &["x"], &[&int_sort]).unwrap(),
+        ];
+        assert!(solver.declare_datatype("D", &ctors).is_err());
+    }
+
+    #[test]
+    fn declare_datatype_rejects_duplicate_selector_names_within_one_call() {
+        let mut solver = ProcessSolver::with_conf(SolverConf::Z3);
+        let int_sort = ProcessSort {
+            repr: "Int".to_string(),
+        };
+        let ctors = [
+            Constructor::new("C1", &["x"], &[&int_sort]).unwrap(),
+            Constructor::new("C2", &["x"], &[&int_sort]).unwrap(),
+        ];
+        assert!(solver.declare_datatype("D", &ctors).is_err());
+    }
+
+    #[test]
+    fn declare_datatype_rejects_a_name_already_used_by_a_record() {
+        let mut solver = ProcessSolver::with_conf(SolverConf::Z3);
+        solver
+            .records
+            .borrow_mut()
+            .insert("D".to_string(), Vec::new());
+        let ctors = [Constructor::<ProcessSort>::new("C", &[], &[]).unwrap()];
+        assert!(solver.declare_datatype("D", &ctors).is_err());
+    }
+
+    #[test]
+    fn declare_datatype_rejects_a_constructor_name_reused_from_an_earlier_declaration() {
+        let mut solver = ProcessSolver::with_conf(SolverConf::Z3);
+        solver
+            .constructors
+            .borrow_mut()
+            .insert("Ctor".to_string(), "A".to_string());
+        let ctors = [Constructor::<ProcessSort>::new("Ctor", &[], &[]).unwrap()];
+        assert!(solver.declare_datatype("B", &ctors).is_err());
+    }
+
+    #[test]
+    fn bitvec_width_parses_width_from_repr() {
+        let sort = ProcessSort {
+            repr: "(_ BitVec 8)".to_string(),
+        };
+        assert_eq!(ProcessSolver::bitvec_width(&sort), Some(8));
+    }
+
+    #[test]
+    fn bitvec_width_returns_none_for_non_bitvec_sort() {
+        let sort = ProcessSort {
+            repr: "Int".to_string(),
+        };
+        assert_eq!(ProcessSolver::bitvec_width(&sort), None);
+    }
+
+    #[test]
+    fn result_sort_sizes_fp_literal_from_its_bitvector_arguments() {
+        let solver = ProcessSolver::with_conf(SolverConf::Z3);
+        let sign = ProcessSort {
+            repr: "(_ BitVec 1)".to_string(),
+        };
+        let exponent = ProcessSort {
+            repr: "(_ BitVec 8)".to_string(),
+        };
+        let significand = ProcessSort {
+            repr: "(_ BitVec 23)".to_string(),
+        };
+        let sort = solver.result_sort(
+            &smt_ops::Fn::FpLiteral,
+            &[&sign, &exponent, &significand],
+        );
+        assert_eq!(sort.repr, "(_ FloatingPoint 8 24)");
+    }
+
+    #[test]
+    fn parse_unsat_core_reply_extracts_labels() {
+        assert_eq!(
+            ProcessSolver::parse_unsat_core_reply("(a c)").unwrap(),
+            vec!["a".to_string(), "c".to_string()]
+        );
+    }
+
+    #[test]
+    fn parse_unsat_core_reply_handles_empty_core() {
+        assert!(ProcessSolver::parse_unsat_core_reply("()")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn parse_unsat_core_reply_rejects_malformed_input() {
+        assert!(ProcessSolver::parse_unsat_core_reply("a c").is_err());
+    }
+}
@@ -0,0 +1,127 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+// Built-in SMT-LIB sort constructors and operators, used by SMTSolver's
+// lookup_sort/apply_sort/lookup_const/apply_fun so that callers don't have to
+// spell out SMT-LIB syntax themselves.
+
+// A built-in SMT-LIB sort or sort constructor.  Nullary sorts (Bool, Int,
+// Real, RoundingMode) and sorts parameterized only by integers (BitVec,
+// FloatingPoint) are looked up directly with lookup_sort; binary sort
+// constructors (Array) are applied to two already-built sorts with
+// apply_sort.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sorts {
+    Bool,
+    Int,
+    Real,
+    BitVec(u32),
+    Array,
+    // FloatingPoint(eb, sb): eb exponent bits, sb significand bits including
+    // the hidden bit, as in SMT-LIB's (_ FloatingPoint eb sb).
+    FloatingPoint(u32, u32),
+    RoundingMode,
+}
+
+// A built-in SMT-LIB function symbol, used as the `Op` case of Function.
+// Variants that carry a name (e.g. RecordSelect, DtConstruct) identify a
+// symbol declared by declare_record_sort/declare_datatype rather than a
+// symbol built into the SMT-LIB core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fn<'a> {
+    True,
+    False,
+
+    // The five IEEE-754 rounding modes, as SMT-LIB RoundingMode constants.
+    RoundNearestTiesToEven,
+    RoundNearestTiesToAway,
+    RoundTowardPositive,
+    RoundTowardNegative,
+    RoundTowardZero,
+
+    // Core theory
+    Not,
+    And,
+    Or,
+    Xor,
+    Implies,
+    Eq,
+    Distinct,
+    Ite,
+
+    // Arithmetic
+    Neg,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    IntDiv,
+    Mod,
+    Abs,
+    Le,
+    Lt,
+    Ge,
+    Gt,
+    ToReal,
+    ToInt,
+
+    // Fixed-size bitvectors
+    BvNot,
+    BvAnd,
+    BvOr,
+    BvXor,
+    BvNeg,
+    BvAdd,
+    BvSub,
+    BvMul,
+    BvUdiv,
+    BvUrem,
+    BvShl,
+    BvLshr,
+    BvUlt,
+
+    // Arrays
+    Select,
+    Store,
+
+    // Records, declared with declare_record_sort
+    RecordSelect(&'a str),
+    RecordUpdate(&'a str),
+
+    // Algebraic datatypes, declared with declare_datatype: the constructor,
+    // a field selector, and the "(_ is Ctor)" tester of a given constructor.
+    DtConstruct(&'a str),
+    DtSelect(&'a str),
+    DtTest(&'a str),
+
+    // IEEE-754 floating-point: the fp literal constructor (sign, exponent,
+    // and significand bitvectors), rounded arithmetic (leading argument is
+    // the RoundingMode), unrounded arithmetic, comparisons, and classifiers.
+    FpLiteral,
+    FpAbs,
+    FpNeg,
+    FpAdd,
+    FpSub,
+    FpMul,
+    FpDiv,
+    FpFma,
+    FpSqrt,
+    FpRem,
+    FpRoundToIntegral,
+    FpMin,
+    FpMax,
+    FpLeq,
+    FpLt,
+    FpGeq,
+    FpGt,
+    FpEq,
+    FpIsNormal,
+    FpIsSubnormal,
+    FpIsZero,
+    FpIsInfinite,
+    FpIsNaN,
+    FpIsNegative,
+    FpIsPositive,
+}